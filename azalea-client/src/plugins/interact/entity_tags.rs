@@ -0,0 +1,53 @@
+//! Lightweight entity tag lookups, analogous to vanilla's data-driven entity
+//! tags (e.g. `minecraft:redirectable_projectile`), so the pick path can ask
+//! "is this entity the kind of thing that gets special-cased" without a
+//! big match over [`EntityKind`] living inline in `pick.rs`.
+
+use azalea_registry::EntityKind;
+use bevy_ecs::prelude::*;
+
+/// Marks an entity as a vanilla `REDIRECTABLE_PROJECTILE` - a fireball-like
+/// projectile that's deliberately given an inflated pick radius so players
+/// can hit (and deflect) it.
+#[derive(Component)]
+pub struct RedirectableProjectile;
+
+/// Keeps [`RedirectableProjectile`] in sync with each entity's kind.
+///
+/// This is a tag lookup, not state that changes after spawn, so entities
+/// that already have the component are skipped.
+pub fn tag_redirectable_projectiles(
+    mut commands: Commands,
+    query: Query<(Entity, &EntityKind), Without<RedirectableProjectile>>,
+) {
+    for (entity, &kind) in &query {
+        if is_redirectable_projectile(kind) {
+            commands.entity(entity).insert(RedirectableProjectile);
+        }
+    }
+}
+
+fn is_redirectable_projectile(kind: EntityKind) -> bool {
+    matches!(
+        kind,
+        EntityKind::Fireball | EntityKind::SmallFireball | EntityKind::WitherSkull
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fireball_like_entities_are_redirectable() {
+        assert!(is_redirectable_projectile(EntityKind::Fireball));
+        assert!(is_redirectable_projectile(EntityKind::SmallFireball));
+        assert!(is_redirectable_projectile(EntityKind::WitherSkull));
+    }
+
+    #[test]
+    fn unrelated_entities_are_not_redirectable() {
+        assert!(!is_redirectable_projectile(EntityKind::Arrow));
+        assert!(!is_redirectable_projectile(EntityKind::Zombie));
+    }
+}
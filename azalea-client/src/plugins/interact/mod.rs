@@ -0,0 +1,26 @@
+pub mod entity_spatial_grid;
+pub mod entity_tags;
+pub mod pick;
+
+use bevy_app::{App, Plugin};
+
+use self::entity_spatial_grid::{EntitySpatialGrid, update_entity_spatial_grid};
+use self::entity_tags::tag_redirectable_projectiles;
+use self::pick::update_hit_result_component;
+use crate::GameTick;
+
+pub struct PickPlugin;
+
+impl Plugin for PickPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EntitySpatialGrid>().add_systems(
+            GameTick,
+            (
+                tag_redirectable_projectiles,
+                update_entity_spatial_grid,
+                update_hit_result_component,
+            )
+                .chain(),
+        );
+    }
+}
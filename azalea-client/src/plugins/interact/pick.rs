@@ -5,19 +5,24 @@ use azalea_core::{
     position::Vec3,
 };
 use azalea_entity::{
-    Attributes, Dead, LocalEntity, LookDirection, Physics, Position,
+    Attributes, Dead, LocalEntity, LookDirection, Physics, Position, Vehicle,
     dimensions::EntityDimensions,
     metadata::{ArmorStandMarker, Marker},
     view_vector,
 };
+use azalea_inventory::Inventory;
 use azalea_physics::{
     clip::{BlockShapeType, ClipContext, FluidPickType},
-    collision::entity_collisions::{PhysicsQuery, get_entities},
+    collision::entity_collisions::{PhysicsQuery, entity_aabb, get_entities},
 };
+use azalea_registry::Item;
 use azalea_world::{Instance, InstanceContainer, InstanceName};
 use bevy_ecs::prelude::*;
 use derive_more::{Deref, DerefMut};
 
+use super::entity_spatial_grid::EntitySpatialGrid;
+use super::entity_tags::RedirectableProjectile;
+
 /// A component that contains the block or entity that the player is currently
 /// looking at.
 #[doc(alias("looking at", "looking at block", "crosshair"))]
@@ -36,10 +41,16 @@ pub fn update_hit_result_component(
             &LookDirection,
             &InstanceName,
             &Attributes,
+            &Inventory,
         ),
         With<LocalEntity>,
     >,
     instance_container: Res<InstanceContainer>,
+    physics_query: PhysicsQuery,
+    pickable_entity_query: PickableEntityQuery,
+    vehicle_query: VehicleQuery,
+    redirectable_projectile_query: RedirectableProjectileQuery,
+    spatial_grid: Option<Res<EntitySpatialGrid>>,
 ) {
     for (
         entity,
@@ -49,9 +60,11 @@ pub fn update_hit_result_component(
         look_direction,
         world_name,
         attributes,
+        inventory,
     ) in &mut query
     {
         let block_pick_range = attributes.block_interaction_range.calculate();
+        let entity_pick_range = attributes.entity_interaction_range.calculate();
 
         let eye_position = position.up(dimensions.eye_height.into());
 
@@ -61,10 +74,19 @@ pub fn update_hit_result_component(
         let world = world_lock.read();
 
         let hit_result = pick(PickOpts {
+            source_entity: entity,
             look_direction: *look_direction,
             eye_position,
             world: &world,
+            world_name,
             block_pick_range,
+            entity_pick_range,
+            fluid_pick_type: fluid_pick_type_for_held_item(inventory),
+            physics_query: &physics_query,
+            predicate: &|candidate| pickable_entity_query.contains(candidate),
+            vehicle_query: &vehicle_query,
+            redirectable_projectile_query: &redirectable_projectile_query,
+            spatial_grid: spatial_grid.as_deref(),
         });
         if let Some(mut hit_result_ref) = hit_result_ref {
             **hit_result_ref = hit_result;
@@ -83,11 +105,44 @@ pub type PickableEntityQuery<'world, 'state, 'a> = Query<
     (Without<Dead>, Without<Marker>, Without<LocalEntity>),
 >;
 
-pub struct PickOpts<'a> {
+pub type VehicleQuery<'world, 'state, 'a> = Query<'world, 'state, &'a Vehicle>;
+
+pub type RedirectableProjectileQuery<'world, 'state> =
+    Query<'world, 'state, Has<RedirectableProjectile>>;
+
+pub struct PickOpts<'world, 'state, 'a, 'b> {
+    source_entity: Entity,
     look_direction: LookDirection,
     eye_position: Vec3,
     world: &'a Instance,
+    world_name: &'a InstanceName,
     block_pick_range: f64,
+    entity_pick_range: f64,
+    fluid_pick_type: FluidPickType,
+    physics_query: &'a PhysicsQuery<'world, 'state, 'b>,
+    predicate: &'a dyn Fn(Entity) -> bool,
+    vehicle_query: &'a VehicleQuery<'world, 'state, 'b>,
+    redirectable_projectile_query: &'a RedirectableProjectileQuery<'world, 'state>,
+    /// When present, [`pick_entity`] only considers candidates in the grid
+    /// cells the pick ray passes through instead of scanning every entity in
+    /// the instance.
+    spatial_grid: Option<&'a EntitySpatialGrid>,
+}
+
+/// Picks the [`FluidPickType`] vanilla would use for the item currently held
+/// in the main hand: source blocks only for an empty bucket (so filling it
+/// targets a fluid source), any fluid for a filled bucket (so emptying it
+/// previews where the fluid would go), and no fluid picking otherwise.
+fn fluid_pick_type_for_held_item(inventory: &Inventory) -> FluidPickType {
+    fluid_pick_type_for_item(inventory.held_item().kind())
+}
+
+fn fluid_pick_type_for_item(item: Item) -> FluidPickType {
+    match item {
+        Item::Bucket => FluidPickType::SourceOnly,
+        Item::WaterBucket | Item::LavaBucket => FluidPickType::Any,
+        _ => FluidPickType::None,
+    }
 }
 
 /// Get the block or entity that a player would be looking at if their eyes were
@@ -96,26 +151,71 @@ pub struct PickOpts<'a> {
 /// If you need to get the block/entity the player is looking at right now, use
 /// [`HitResultComponent`].
 ///
-/// Also see [`pick_block`].
-pub fn pick(opts: PickOpts<'_>) -> HitResult {
+/// Also see [`pick_block`] and [`pick_entity`].
+pub fn pick(opts: PickOpts<'_, '_, '_, '_>) -> HitResult {
     // vanilla does extra math here to calculate the pick result in between ticks by
     // interpolating, but since clients can still only interact on exact ticks, that
     // isn't relevant for us.
 
-    let mut max_range = opts.block_pick_range;
+    let max_range = opts.block_pick_range.max(opts.entity_pick_range);
 
-    let block_hit_result = pick_block(
+    let raw_block_hit_result = pick_block(
         opts.look_direction,
         opts.eye_position,
         &opts.world.chunks,
         max_range,
+        opts.fluid_pick_type,
     );
-
-    filter_hit_result(
-        HitResult::Block(block_hit_result),
+    let block_distance_squared = opts
+        .eye_position
+        .distance_squared_to(raw_block_hit_result.location);
+    // pick_block walks the ray all the way to max_range when it doesn't hit
+    // anything solid, so a block distance right at the end of the ray means it
+    // was a miss (mirrors vanilla's `hitresult.getType() == HitResult.Type.MISS`).
+    let block_was_miss = block_distance_squared >= max_range * max_range;
+    let block_hit_result = filter_hit_result(
+        HitResult::Block(raw_block_hit_result),
         opts.eye_position,
         opts.block_pick_range,
-    )
+    );
+
+    let view_vector = view_vector(opts.look_direction);
+    let end_position = opts.eye_position + (view_vector * max_range);
+    let search_aabb = AABB::new(opts.eye_position, end_position).inflate_all(1.0);
+
+    // when a spatial grid is available, only the entities in the cells the ray
+    // actually passes through are ever looked at below, instead of every entity
+    // overlapping search_aabb; otherwise we fall back to that full scan.
+    let grid_candidates = opts
+        .spatial_grid
+        .map(|grid| grid.entities_along_ray(opts.world_name, opts.eye_position, end_position));
+
+    let entity_hit_result = pick_entity(PickEntityOpts {
+        source_entity: opts.source_entity,
+        eye_position: opts.eye_position,
+        end_position,
+        world: opts.world,
+        pick_range_squared: block_distance_squared,
+        predicate: opts.predicate,
+        aabb: &search_aabb,
+        grid_candidates: grid_candidates.as_deref(),
+        physics_query: opts.physics_query,
+        vehicle_query: opts.vehicle_query,
+        redirectable_projectile_query: opts.redirectable_projectile_query,
+    });
+
+    let Some(entity_hit_result) = entity_hit_result else {
+        return block_hit_result;
+    };
+
+    let entity_distance_squared = opts
+        .eye_position
+        .distance_squared_to(entity_hit_result.location);
+    if entity_distance_squared < block_distance_squared || block_was_miss {
+        HitResult::Entity(entity_hit_result)
+    } else {
+        block_hit_result
+    }
 }
 
 fn filter_hit_result(hit_result: HitResult, eye_position: Vec3, range: f64) -> HitResult {
@@ -137,6 +237,7 @@ pub fn pick_block(
     eye_position: Vec3,
     chunks: &azalea_world::ChunkStorage,
     pick_range: f64,
+    fluid_pick_type: FluidPickType,
 ) -> BlockHitResult {
     let view_vector = view_vector(look_direction);
     let end_position = eye_position + (view_vector * pick_range);
@@ -147,11 +248,71 @@ pub fn pick_block(
             from: eye_position,
             to: end_position,
             block_shape_type: BlockShapeType::Outline,
-            fluid_pick_type: FluidPickType::None,
+            fluid_pick_type,
         },
     )
 }
 
+/// Whether nothing solid blocks the view from `eye_position` to
+/// `target_eye_position`, analogous to vanilla's `Entity.hasLineOfSight`.
+///
+/// Builds on the same [`clip`](azalea_physics::clip::clip) plumbing as
+/// [`pick_block`], just with a collider block shape (instead of outline) and
+/// no fluid picking, since line-of-sight only cares about solid obstructions.
+///
+/// If `field_of_view` is given as `(look_direction, max_half_angle_degrees)`,
+/// targets outside that half-angle are rejected up front, before paying for
+/// the block raytrace.
+pub fn has_line_of_sight(
+    eye_position: Vec3,
+    target_eye_position: Vec3,
+    chunks: &azalea_world::ChunkStorage,
+    field_of_view: Option<(LookDirection, f64)>,
+) -> bool {
+    if let Some((look_direction, max_half_angle_degrees)) = field_of_view {
+        if !is_within_field_of_view(
+            eye_position,
+            target_eye_position,
+            look_direction,
+            max_half_angle_degrees,
+        ) {
+            return false;
+        }
+    }
+
+    let hit_result = azalea_physics::clip::clip(
+        chunks,
+        ClipContext {
+            from: eye_position,
+            to: target_eye_position,
+            block_shape_type: BlockShapeType::Collider,
+            fluid_pick_type: FluidPickType::None,
+        },
+    );
+
+    let target_distance_squared = eye_position.distance_squared_to(target_eye_position);
+    let hit_distance_squared = eye_position.distance_squared_to(hit_result.location);
+    hit_distance_squared >= target_distance_squared
+}
+
+/// Whether `target_eye_position` lies within `max_half_angle_degrees` of
+/// where `look_direction` is pointing from `eye_position`. A target exactly
+/// at `eye_position` is always considered in view, since there's no
+/// meaningful angle to reject it by.
+fn is_within_field_of_view(
+    eye_position: Vec3,
+    target_eye_position: Vec3,
+    look_direction: LookDirection,
+    max_half_angle_degrees: f64,
+) -> bool {
+    let to_target = target_eye_position - eye_position;
+    if to_target.length_squared() == 0. {
+        return true;
+    }
+    let angle_to_target = view_vector(look_direction).dot(to_target.normalize());
+    angle_to_target >= max_half_angle_degrees.to_radians().cos()
+}
+
 struct PickEntityOpts<'world, 'state, 'a, 'b> {
     source_entity: Entity,
     eye_position: Vec3,
@@ -160,7 +321,22 @@ struct PickEntityOpts<'world, 'state, 'a, 'b> {
     pick_range_squared: f64,
     predicate: &'a dyn Fn(Entity) -> bool,
     aabb: &'a AABB,
+    /// When present, only these entities are considered (looked up directly
+    /// via `physics_query`) instead of scanning every entity `aabb` overlaps.
+    grid_candidates: Option<&'a [Entity]>,
     physics_query: &'a PhysicsQuery<'world, 'state, 'b>,
+    vehicle_query: &'a VehicleQuery<'world, 'state, 'b>,
+    redirectable_projectile_query: &'a RedirectableProjectileQuery<'world, 'state>,
+}
+
+/// Walks the mount/passenger chain up from `entity` to find the vehicle at
+/// the top of it (or `entity` itself if it isn't riding anything).
+fn root_vehicle(entity: Entity, vehicle_query: &VehicleQuery) -> Entity {
+    let mut root = entity;
+    while let Ok(vehicle) = vehicle_query.get(root) {
+        root = vehicle.0;
+    }
+    root
 }
 
 // port of getEntityHitResult
@@ -168,18 +344,50 @@ fn pick_entity(opts: PickEntityOpts) -> Option<EntityHitResult> {
     let mut picked_distance_squared = opts.pick_range_squared;
     let mut result = None;
 
-    for (candidate, candidate_aabb) in get_entities(
-        opts.world,
-        Some(opts.source_entity),
-        opts.aabb,
-        opts.predicate,
-        opts.physics_query,
-    ) {
-        // TODO: if the entity is "REDIRECTABLE_PROJECTILE" then this should be 1.0.
-        // azalea needs support for entity tags first for this to be possible. see
-        // getPickRadius in decompiled minecraft source
-        let candidate_pick_radius = 0.;
+    let source_root_vehicle = root_vehicle(opts.source_entity, opts.vehicle_query);
+
+    // Broad-phase-then-predicate-then-narrow-phase: reject candidates whose
+    // bounding sphere the ray doesn't even graze (or that lie entirely beyond
+    // the current best distance) before running `opts.predicate` - an ECS
+    // lookup - and the exact (and pricier) AABB clip below, so both only ever
+    // pay for candidates that already survived the cheap geometric tests.
+    // This is the same broad-then-narrow trick 0ad uses
+    // to keep ray-vs-crowd picking from degrading into an O(n) sweep of exact
+    // clips (and, here, of predicate lookups too).
+    let mut visit_candidate = |candidate: Entity, candidate_aabb: AABB| {
+        if candidate == opts.source_entity {
+            return;
+        }
+
+        // port of getPickRadius: redirectable projectiles (fireballs etc.) get an
+        // inflated pick radius so they can actually be hit and deflected. This
+        // has to happen before the broad-phase sphere test below, since the
+        // sphere needs to cover the same (possibly inflated) volume the exact
+        // clip will later test against - testing the un-inflated AABB's sphere
+        // would reject rays that only clip the inflated volume.
+        let candidate_pick_radius = if opts
+            .redirectable_projectile_query
+            .get(candidate)
+            .unwrap_or(false)
+        {
+            1.0
+        } else {
+            0.
+        };
         let candidate_aabb = candidate_aabb.inflate_all(candidate_pick_radius);
+
+        if !ray_intersects_bounding_sphere(
+            opts.eye_position,
+            opts.end_position,
+            &candidate_aabb,
+            picked_distance_squared,
+        ) {
+            return;
+        }
+        if !(opts.predicate)(candidate) {
+            return;
+        }
+
         let clip_location = candidate_aabb.clip(opts.eye_position, opts.end_position);
 
         if candidate_aabb.contains(opts.eye_position) {
@@ -193,21 +401,226 @@ fn pick_entity(opts: PickEntityOpts) -> Option<EntityHitResult> {
         } else if let Some(clip_location) = clip_location {
             let distance_squared = opts.eye_position.distance_squared_to(clip_location);
             if distance_squared < picked_distance_squared || picked_distance_squared == 0. {
-                // TODO: don't pick the entity we're riding on
-                // if candidate_root_vehicle == entity_root_vehicle {
-                //     if picked_distance_squared == 0. {
-                //         picked_entity = Some(candidate);
-                //         picked_location = Some(clip_location);
-                //     }
-                // } else {
-                result = Some(EntityHitResult {
-                    location: clip_location,
-                    entity: candidate,
-                });
-                picked_distance_squared = distance_squared;
+                // don't let a mounted bot nearest-distance-pick the boat/horse it's
+                // riding (or a fellow passenger on it) - it can still be picked if
+                // the eye is literally inside its box, via the `contains` branch above.
+                if root_vehicle(candidate, opts.vehicle_query) == source_root_vehicle {
+                    if picked_distance_squared == 0. {
+                        result = Some(EntityHitResult {
+                            location: clip_location,
+                            entity: candidate,
+                        });
+                    }
+                } else {
+                    result = Some(EntityHitResult {
+                        location: clip_location,
+                        entity: candidate,
+                    });
+                    picked_distance_squared = distance_squared;
+                }
+            }
+        }
+    };
+
+    // When a spatial grid is available, only look at the entities it says the
+    // ray actually passes near, fetching each one's AABB directly instead of
+    // scanning `opts.aabb` for every entity the way `get_entities` does below -
+    // that full scan is exactly what the grid exists to avoid. Without a grid,
+    // fall back to that scan, deferring `opts.predicate` to `visit_candidate`
+    // (via a throwaway `&|_| true`) so it still only runs post-sphere-test.
+    match opts.grid_candidates {
+        Some(grid_candidates) => {
+            for &candidate in grid_candidates {
+                if let Some(candidate_aabb) = entity_aabb(opts.physics_query, candidate) {
+                    visit_candidate(candidate, candidate_aabb);
+                }
+            }
+        }
+        None => {
+            for (candidate, candidate_aabb) in get_entities(
+                opts.world,
+                Some(opts.source_entity),
+                opts.aabb,
+                &|_| true,
+                opts.physics_query,
+            ) {
+                visit_candidate(candidate, *candidate_aabb);
             }
         }
     }
 
     result
 }
+
+/// Cheap ray-vs-sphere test used to cull entities before they reach the exact
+/// (and more expensive) AABB clip in [`pick_entity`].
+///
+/// `range_squared` is the current best squared pick distance; candidates whose
+/// sphere lies entirely beyond it can't win regardless of whether the ray
+/// actually touches them, so it doubles as a distance-squared gate.
+fn ray_intersects_bounding_sphere(
+    eye_position: Vec3,
+    end_position: Vec3,
+    aabb: &AABB,
+    range_squared: f64,
+) -> bool {
+    let center = aabb.center();
+    let radius_squared = (0.5 * aabb.diagonal_length()).powi(2);
+
+    let ray_length = eye_position.distance_to(end_position);
+    if ray_length == 0. {
+        return eye_position.distance_squared_to(center) <= radius_squared;
+    }
+    let ray_direction = (end_position - eye_position) / ray_length;
+
+    let range = range_squared.sqrt().min(ray_length);
+    let to_center = center - eye_position;
+    let projected_distance = to_center.dot(ray_direction).clamp(0., range);
+    let closest_point = eye_position + ray_direction * projected_distance;
+
+    closest_point.distance_squared_to(center) <= radius_squared
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::{system::SystemState, world::World};
+
+    use super::*;
+
+    #[test]
+    fn ray_through_aabb_center_intersects() {
+        let aabb = AABB::new(Vec3::new(-1., -1., -1.), Vec3::new(1., 1., 1.));
+        assert!(ray_intersects_bounding_sphere(
+            Vec3::new(-5., 0., 0.),
+            Vec3::new(5., 0., 0.),
+            &aabb,
+            f64::MAX,
+        ));
+    }
+
+    #[test]
+    fn ray_missing_aabb_does_not_intersect() {
+        let aabb = AABB::new(Vec3::new(-1., -1., -1.), Vec3::new(1., 1., 1.));
+        assert!(!ray_intersects_bounding_sphere(
+            Vec3::new(-5., 10., 0.),
+            Vec3::new(5., 10., 0.),
+            &aabb,
+            f64::MAX,
+        ));
+    }
+
+    #[test]
+    fn ray_intersects_geometrically_but_beyond_range_squared_does_not_count() {
+        let aabb = AABB::new(Vec3::new(-1., -1., -1.), Vec3::new(1., 1., 1.));
+        assert!(!ray_intersects_bounding_sphere(
+            Vec3::new(-5., 0., 0.),
+            Vec3::new(5., 0., 0.),
+            &aabb,
+            1.0,
+        ));
+    }
+
+    #[test]
+    fn zero_length_ray_inside_aabb_intersects() {
+        let aabb = AABB::new(Vec3::new(-1., -1., -1.), Vec3::new(1., 1., 1.));
+        assert!(ray_intersects_bounding_sphere(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(0., 0., 0.),
+            &aabb,
+            f64::MAX,
+        ));
+    }
+
+    #[test]
+    fn target_straight_ahead_is_within_field_of_view() {
+        let eye_position = Vec3::new(0., 0., 0.);
+        let look_direction = LookDirection::default();
+        let forward = view_vector(look_direction);
+        let target_eye_position = eye_position + forward * 10.;
+        assert!(is_within_field_of_view(
+            eye_position,
+            target_eye_position,
+            look_direction,
+            60.,
+        ));
+    }
+
+    #[test]
+    fn target_directly_behind_is_not_within_field_of_view() {
+        let eye_position = Vec3::new(0., 0., 0.);
+        let look_direction = LookDirection::default();
+        let forward = view_vector(look_direction);
+        let target_eye_position = eye_position - forward * 10.;
+        assert!(!is_within_field_of_view(
+            eye_position,
+            target_eye_position,
+            look_direction,
+            60.,
+        ));
+    }
+
+    #[test]
+    fn target_at_eye_position_is_always_within_field_of_view() {
+        let eye_position = Vec3::new(1., 2., 3.);
+        assert!(is_within_field_of_view(
+            eye_position,
+            eye_position,
+            LookDirection::default(),
+            0.,
+        ));
+    }
+
+    #[test]
+    fn fluid_pick_type_for_empty_bucket_is_source_only() {
+        assert_eq!(
+            fluid_pick_type_for_item(Item::Bucket),
+            FluidPickType::SourceOnly
+        );
+    }
+
+    #[test]
+    fn fluid_pick_type_for_filled_bucket_is_any() {
+        assert_eq!(
+            fluid_pick_type_for_item(Item::WaterBucket),
+            FluidPickType::Any
+        );
+        assert_eq!(
+            fluid_pick_type_for_item(Item::LavaBucket),
+            FluidPickType::Any
+        );
+    }
+
+    #[test]
+    fn fluid_pick_type_for_unrelated_item_is_none() {
+        assert_eq!(
+            fluid_pick_type_for_item(Item::Stick),
+            FluidPickType::None
+        );
+    }
+
+    #[test]
+    fn root_vehicle_of_unmounted_entity_is_itself() {
+        let mut world = World::new();
+        let entity = world.spawn_empty().id();
+
+        let mut system_state: SystemState<VehicleQuery> = SystemState::new(&mut world);
+        let vehicle_query = system_state.get(&world);
+
+        assert_eq!(root_vehicle(entity, &vehicle_query), entity);
+    }
+
+    #[test]
+    fn root_vehicle_walks_the_mount_chain_to_the_top() {
+        let mut world = World::new();
+        let boat = world.spawn_empty().id();
+        let passenger = world.spawn(Vehicle(boat)).id();
+        let backseat_passenger = world.spawn(Vehicle(passenger)).id();
+
+        let mut system_state: SystemState<VehicleQuery> = SystemState::new(&mut world);
+        let vehicle_query = system_state.get(&world);
+
+        assert_eq!(root_vehicle(boat, &vehicle_query), boat);
+        assert_eq!(root_vehicle(passenger, &vehicle_query), boat);
+        assert_eq!(root_vehicle(backseat_passenger, &vehicle_query), boat);
+    }
+}